@@ -0,0 +1,180 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Shared attempt/deadline bookkeeping so every worker agrees on when to
+/// stop starting new attempts, without a central coordinator in the hot
+/// path of each attempt.
+///
+/// `stop` and `succeeded` are deliberately separate flags: `stop` just
+/// means "don't reserve any more attempts" and is set on success, on a
+/// non-retryable abort, and on budget exhaustion alike, while `succeeded`
+/// is set on exactly one path. The monitor thread that decides whether to
+/// report an abort must key off `succeeded`, not `stop` -- otherwise it
+/// can observe "stop is set" immediately after a real success and report
+/// a false abort before the process exits.
+pub struct AttemptBudget {
+    attempts: AtomicUsize,
+    max_attempts: Option<usize>,
+    deadline: Option<Instant>,
+    stop: AtomicBool,
+    succeeded: AtomicBool,
+}
+
+impl AttemptBudget {
+    pub fn new(max_attempts: Option<usize>, overall_deadline: Option<Duration>) -> Self {
+        Self {
+            attempts: AtomicUsize::new(0),
+            max_attempts,
+            deadline: overall_deadline.map(|d| Instant::now() + d),
+            stop: AtomicBool::new(false),
+            succeeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Claims the next attempt slot, returning its 1-based attempt number,
+    /// or `None` if the run has been stopped (success, abort, or budget
+    /// exhaustion) and the caller should stop looping.
+    pub fn reserve_attempt(&self) -> Option<usize> {
+        if self.stop.load(Ordering::SeqCst) || self.deadline_passed() {
+            return None;
+        }
+        // fetch_update only commits the increment while the closure keeps
+        // returning Some, so a worker that loses the race to reach
+        // max_attempts never bumps the counter past it -- unlike a
+        // separate load-then-fetch_add, which lets every contending
+        // worker increment past the cap before any of them observes the
+        // new count.
+        self.attempts
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |attempts| {
+                if self.max_attempts.is_some_and(|max| attempts >= max) {
+                    None
+                } else {
+                    Some(attempts + 1)
+                }
+            })
+            .ok()
+            .map(|previous| previous + 1)
+    }
+
+    fn deadline_passed(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    fn max_attempts_reached(&self) -> bool {
+        self.max_attempts
+            .is_some_and(|max| self.attempts.load(Ordering::SeqCst) >= max)
+    }
+
+    /// True once the attempt budget or overall deadline has been used up
+    /// *without* a success. This is what the independent monitor thread
+    /// should poll -- it ignores the cooperative `stop` flag so that a
+    /// success landing at the same instant can never be misreported as
+    /// an abort.
+    pub fn budget_exhausted(&self) -> bool {
+        !self.succeeded() && (self.deadline_passed() || self.max_attempts_reached())
+    }
+
+    /// Marks the run as succeeded and stops every worker from reserving
+    /// further attempts.
+    pub fn declare_success(&self) {
+        self.succeeded.store(true, Ordering::SeqCst);
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.succeeded.load(Ordering::SeqCst)
+    }
+
+    /// Stops every worker from reserving further attempts without
+    /// declaring success, e.g. once a non-retryable fail-pattern aborts
+    /// the run.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn total_attempts(&self) -> usize {
+        self.attempts.load(Ordering::SeqCst)
+    }
+
+    /// Describes why `budget_exhausted()` is true, for the `Aborted` CI
+    /// event and the matching log line. Only meaningful to call once
+    /// exhaustion has actually been observed.
+    pub fn exhaustion_reason(&self) -> String {
+        if self.deadline_passed() {
+            "overall deadline exceeded".to_string()
+        } else if let Some(max) = self.max_attempts {
+            if self.max_attempts_reached() {
+                format!("max attempts ({max}) reached")
+            } else {
+                "stopped".to_string()
+            }
+        } else {
+            "stopped".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn success_does_not_report_budget_exhausted() {
+        let budget = AttemptBudget::new(Some(1), None);
+        assert_eq!(budget.reserve_attempt(), Some(1));
+        budget.declare_success();
+        assert!(!budget.budget_exhausted());
+        assert!(budget.reserve_attempt().is_none());
+    }
+
+    #[test]
+    fn max_attempts_reached_is_reported_when_not_succeeded() {
+        let budget = AttemptBudget::new(Some(2), None);
+        assert_eq!(budget.reserve_attempt(), Some(1));
+        assert_eq!(budget.reserve_attempt(), Some(2));
+        assert!(budget.reserve_attempt().is_none());
+        assert!(budget.budget_exhausted());
+        assert_eq!(budget.exhaustion_reason(), "max attempts (2) reached");
+    }
+
+    #[test]
+    fn overall_deadline_reported_when_passed() {
+        let budget = AttemptBudget::new(None, Some(Duration::from_millis(1)));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(budget.budget_exhausted());
+        assert_eq!(budget.exhaustion_reason(), "overall deadline exceeded");
+        assert!(budget.reserve_attempt().is_none());
+    }
+
+    #[test]
+    fn stop_without_success_does_not_count_as_budget_exhausted() {
+        let budget = AttemptBudget::new(None, None);
+        budget.stop();
+        assert!(budget.reserve_attempt().is_none());
+        assert!(!budget.budget_exhausted());
+    }
+
+    #[test]
+    fn concurrent_reservations_never_exceed_max_attempts() {
+        use std::sync::Arc;
+
+        let max = 10;
+        let budget = Arc::new(AttemptBudget::new(Some(max), None));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = Arc::clone(&budget);
+                std::thread::spawn(move || {
+                    let mut reserved = 0;
+                    while budget.reserve_attempt().is_some() {
+                        reserved += 1;
+                    }
+                    reserved
+                })
+            })
+            .collect();
+
+        let total_reserved: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(total_reserved, max);
+        assert_eq!(budget.total_attempts(), max);
+    }
+}
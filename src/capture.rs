@@ -0,0 +1,89 @@
+use std::io::Read;
+use std::process::{ChildStderr, ChildStdout};
+use std::thread;
+
+use anyhow::{Context, Result};
+
+/// Captured output from both a child's stdout and stderr.
+#[derive(Debug, Default, Clone)]
+pub struct AttemptOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl AttemptOutput {
+    /// Both streams concatenated, for pattern classification that doesn't
+    /// care which stream a line came from. A separating newline is forced
+    /// between them when stdout doesn't already end in one, so a stdout
+    /// line with no trailing newline (e.g. a progress line) can't fuse
+    /// with stderr's first line and hide a pattern match.
+    pub fn combined(&self) -> String {
+        let mut combined = self.stdout.clone();
+        if !combined.is_empty() && !combined.ends_with('\n') {
+            combined.push('\n');
+        }
+        combined.push_str(&self.stderr);
+        combined
+    }
+}
+
+/// Reads `stdout` and `stderr` to completion concurrently on two joined
+/// reader threads. A generator that writes more than a pipe buffer's
+/// worth to one stream without us reading it would otherwise block
+/// forever, hanging the worker that's waiting on the other stream.
+pub fn drain(stdout: ChildStdout, stderr: ChildStderr) -> Result<AttemptOutput> {
+    let stdout_reader = thread::spawn(move || read_all(stdout));
+    let stderr_reader = thread::spawn(move || read_all(stderr));
+
+    let stdout = stdout_reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("stdout reader thread panicked"))?
+        .context("failed to read subprocess stdout")?;
+    let stderr = stderr_reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("stderr reader thread panicked"))?
+        .context("failed to read subprocess stderr")?;
+
+    Ok(AttemptOutput { stdout, stderr })
+}
+
+fn read_all<R: Read>(mut reader: R) -> Result<String> {
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_inserts_separator_when_stdout_lacks_trailing_newline() {
+        let output = AttemptOutput {
+            stdout: "Generating... 42%".to_string(),
+            stderr: "YamlError: bad yaml\n".to_string(),
+        };
+        assert_eq!(
+            output.combined(),
+            "Generating... 42%\nYamlError: bad yaml\n"
+        );
+    }
+
+    #[test]
+    fn combined_does_not_duplicate_existing_separator() {
+        let output = AttemptOutput {
+            stdout: "done\n".to_string(),
+            stderr: "ok\n".to_string(),
+        };
+        assert_eq!(output.combined(), "done\nok\n");
+    }
+
+    #[test]
+    fn combined_handles_empty_stdout() {
+        let output = AttemptOutput {
+            stdout: String::new(),
+            stderr: "ok\n".to_string(),
+        };
+        assert_eq!(output.combined(), "ok\n");
+    }
+}
@@ -0,0 +1,122 @@
+use regex::Regex;
+use tracing::{error, info, warn};
+
+/// Compiled `--success-pattern`/`--fail-pattern`/`--abort-pattern` sets,
+/// shared read-only across all workers via the thread scope.
+#[derive(Debug, Default)]
+pub struct Classifier {
+    success: Vec<Regex>,
+    fail: Vec<Regex>,
+    abort: Vec<Regex>,
+}
+
+/// The verdict `Classifier::classify` reaches after scanning an attempt's
+/// combined stdout/stderr.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// No configured pattern matched; the caller should fall back to
+    /// whatever other success signal it has (e.g. the zip watcher).
+    Unknown,
+    /// A `--success-pattern` matched the given line.
+    Success { line: String },
+    /// A `--fail-pattern` matched the given line; retryable.
+    Failure { line: String },
+    /// An `--abort-pattern` matched the given line, e.g. a YAML parse
+    /// error -- retrying wouldn't help, so the caller should give up on
+    /// the whole run instead of just this attempt.
+    Abort { line: String },
+}
+
+impl Classifier {
+    pub fn new(
+        success_patterns: &[String],
+        fail_patterns: &[String],
+        abort_patterns: &[String],
+    ) -> anyhow::Result<Self> {
+        let compile = |patterns: &[String]| -> anyhow::Result<Vec<Regex>> {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p).map_err(anyhow::Error::from))
+                .collect()
+        };
+        Ok(Self {
+            success: compile(success_patterns)?,
+            fail: compile(fail_patterns)?,
+            abort: compile(abort_patterns)?,
+        })
+    }
+
+    /// Scans `output` line by line. An `--abort-pattern` match takes
+    /// priority over everything else since it marks the error as
+    /// non-retryable, then a `--fail-pattern` match (failures take
+    /// priority over success since a generator can print reassuring
+    /// progress lines before dying), then a `--success-pattern` match,
+    /// otherwise `Verdict::Unknown`.
+    pub fn classify(&self, output: &str) -> Verdict {
+        for line in output.lines() {
+            if let Some(pattern) = self.abort.iter().find(|re| re.is_match(line)) {
+                error!(%line, %pattern, "abort-pattern matched");
+                return Verdict::Abort {
+                    line: line.to_string(),
+                };
+            }
+        }
+        for line in output.lines() {
+            if let Some(pattern) = self.fail.iter().find(|re| re.is_match(line)) {
+                warn!(%line, %pattern, "fail-pattern matched");
+                return Verdict::Failure {
+                    line: line.to_string(),
+                };
+            }
+        }
+        for line in output.lines() {
+            if let Some(pattern) = self.success.iter().find(|re| re.is_match(line)) {
+                info!(%line, %pattern, "success-pattern matched");
+                return Verdict::Success {
+                    line: line.to_string(),
+                };
+            }
+        }
+        Verdict::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier(success: &[&str], fail: &[&str], abort: &[&str]) -> Classifier {
+        let to_strings = |pats: &[&str]| pats.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        Classifier::new(&to_strings(success), &to_strings(fail), &to_strings(abort)).unwrap()
+    }
+
+    #[test]
+    fn abort_pattern_takes_priority_over_fail_and_success() {
+        let c = classifier(&["^ok$"], &["^fail$"], &["^yaml parse error$"]);
+        let output = "ok\nfail\nyaml parse error\n";
+        assert_eq!(
+            c.classify(output),
+            Verdict::Abort {
+                line: "yaml parse error".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn fail_pattern_still_takes_priority_over_success_without_abort() {
+        let c = classifier(&["^ok$"], &["^fail$"], &[]);
+        let output = "ok\nfail\n";
+        assert_eq!(
+            c.classify(output),
+            Verdict::Failure {
+                line: "fail".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn no_pattern_match_is_unknown() {
+        let c = classifier(&["^ok$"], &["^fail$"], &["^abort$"]);
+        assert_eq!(c.classify("something else"), Verdict::Unknown);
+    }
+}
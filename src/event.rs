@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// One line of the `--format json` event stream, written
+/// newline-delimited to stdout. Lets a wrapping process (CI, a release
+/// script) react to the run without scraping tracing's human-oriented log
+/// lines, which go to stderr/journald instead in that mode.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event")]
+pub enum Event {
+    AttemptStarted {
+        worker: usize,
+        pid: i32,
+    },
+    AttemptFinished {
+        worker: usize,
+        exit_code: Option<i32>,
+        duration_ms: u128,
+        /// Zips observed by the filesystem watcher during this attempt's
+        /// window. With `--jobs` > 1 this counts every worker's zips, not
+        /// just this attempt's own, so a nonzero delta on a failed
+        /// attempt can simply mean another worker's zip landed at the
+        /// same time -- it's a timing signal, not proof this attempt
+        /// produced a zip.
+        zip_delta: i64,
+    },
+    Success {
+        path: Option<PathBuf>,
+        total_attempts: usize,
+    },
+    Aborted {
+        reason: String,
+    },
+}
+
+/// Prints `event` as a single JSON line to stdout when `enabled`, silently
+/// dropping serialization failures (which would only happen for a type
+/// bug, never bad runtime data).
+pub fn emit(enabled: bool, event: &Event) {
+    if !enabled {
+        return;
+    }
+    if let Ok(line) = serde_json::to_string(event) {
+        println!("{line}");
+    }
+}
@@ -1,20 +1,84 @@
-use std::io::{Read, Write};
+mod budget;
+mod capture;
+mod classify;
+mod event;
+mod process_group;
+mod zip_watcher;
+
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{self, Child, Command, Stdio};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{fs, thread};
 
-use anyhow::Result;
-use clap::Parser;
-use nix::sys::signal::{self, Signal};
-use nix::unistd::Pid;
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use command_group::{CommandGroup, GroupChild};
 use tracing::level_filters::LevelFilter;
 use tracing::{debug, error, info, instrument};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, EnvFilter, Registry};
 
+use budget::AttemptBudget;
+use classify::{Classifier, Verdict};
+use event::Event;
+use process_group::GroupRegistry;
+use zip_watcher::NewZip;
+
+/// Output mode for the run: human-oriented tracing, or a `--format json`
+/// machine-readable event stream for CI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Text => write!(f, "text"),
+            Format::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Starting delay for a worker's exponential backoff after a failed
+/// attempt, doubled per consecutive failure up to `BACKOFF_CAP`.
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_CAP: Duration = Duration::from_secs(30);
+
+/// How often the budget monitor re-checks the attempt count and overall
+/// deadline.
+const BUDGET_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whichever signal convinced the checker thread that the run succeeded:
+/// a genuinely new zip landing on disk, or a worker matching a
+/// `--success-pattern` against an attempt's output.
+enum SuccessSignal {
+    Zip(NewZip),
+    Pattern { worker: usize, line: String },
+}
+
+/// How long a worker's process group is given to exit cleanly after
+/// `SIGTERM` before we escalate to `SIGKILL`.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Everything an attempt needs that doesn't change between attempts or
+/// workers, bundled so `run_attempt` doesn't grow an argument per
+/// feature. `zip_counter` is bumped once per zip the filesystem watcher
+/// reports, so `zip_delta` can be read off as a cheap atomic diff instead
+/// of re-scanning `archipelago_dir` on every single attempt.
+struct AttemptConfig {
+    classifier: Arc<Classifier>,
+    attempt_timeout: Option<Duration>,
+    json_events: bool,
+    zip_counter: Arc<AtomicUsize>,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -53,12 +117,63 @@ struct Args {
         help = "args passed through to generate.py"
     )]
     options: Vec<String>,
+
+    #[arg(
+        long = "success-pattern",
+        value_name = "REGEX",
+        help = "a regex matched against attempt output; a hit confirms success independently of the zip watcher (repeatable)"
+    )]
+    success_patterns: Vec<String>,
+
+    #[arg(
+        long = "fail-pattern",
+        value_name = "REGEX",
+        help = "a regex matched against attempt output; a hit marks that attempt as a terminal failure (repeatable)"
+    )]
+    fail_patterns: Vec<String>,
+
+    #[arg(
+        long = "abort-pattern",
+        value_name = "REGEX",
+        help = "a regex matched against attempt output; a hit is non-retryable and aborts the whole run instead of just that attempt (repeatable)"
+    )]
+    abort_patterns: Vec<String>,
+
+    #[arg(
+        long = "max-attempts",
+        value_name = "N",
+        help = "total attempts across all workers before giving up and exiting non-zero"
+    )]
+    max_attempts: Option<usize>,
+
+    #[arg(
+        long = "attempt-timeout",
+        value_name = "SECS",
+        help = "kill and reap a single attempt that runs longer than this, counting it as a failure"
+    )]
+    attempt_timeout: Option<u64>,
+
+    #[arg(
+        long = "overall-deadline",
+        value_name = "SECS",
+        help = "give up and exit non-zero if no success within this many seconds of starting"
+    )]
+    overall_deadline: Option<u64>,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = Format::Text,
+        help = "'json' emits a newline-delimited JSON event stream on stdout for CI, moving the pretty tracing output to stderr"
+    )]
+    format: Format,
 }
 
 fn main() -> Result<()> {
-    init_tracing()?;
     let args = Args::parse();
+    init_tracing(args.format)?;
     info!(?args);
+    let json_events = args.format == Format::Json;
     let jobs = args.jobs.unwrap_or(4);
     info!("jobs: {}", jobs);
     let archipelago_dir = args
@@ -77,80 +192,160 @@ fn main() -> Result<()> {
         .unwrap_or_else(|| "/run/current-system/sw/bin/archipelago".into());
     info!("archipelago command: '{}'", bin);
 
-    let (ziptx, ziprx) = mpsc::sync_channel::<usize>(4_usize * jobs as usize);
+    let classifier = Arc::new(Classifier::new(
+        &args.success_patterns,
+        &args.fail_patterns,
+        &args.abort_patterns,
+    )?);
+    let attempt_timeout = args.attempt_timeout.map(Duration::from_secs);
+    let budget = Arc::new(AttemptBudget::new(
+        args.max_attempts,
+        args.overall_deadline.map(Duration::from_secs),
+    ));
+    let zip_counter = Arc::new(AtomicUsize::new(0));
+    let attempt_config = Arc::new(AttemptConfig {
+        classifier: Arc::clone(&classifier),
+        attempt_timeout,
+        json_events,
+        zip_counter: Arc::clone(&zip_counter),
+    });
+
+    let (signaltx, signalrx) = mpsc::sync_channel::<SuccessSignal>(4_usize * jobs as usize);
 
     let initial_zips = how_many_zips(&archipelago_dir).unwrap_or_default();
     info!(
         "there appears to be {} previously generated multiworlds",
         initial_zips
     );
+    // Keeps the watcher alive for the lifetime of the run; dropping it
+    // would stop the filesystem watch.
+    let (zip_tx, zip_rx) = mpsc::sync_channel::<NewZip>(4_usize * jobs as usize);
+    let _zip_watcher = zip_watcher::watch_zips(&archipelago_dir, zip_tx)?;
+    let zip_signaltx = signaltx.clone();
+    let zip_rx_counter = Arc::clone(&zip_counter);
+    let _ = thread::spawn(move || {
+        while let Ok(new_zip) = zip_rx.recv() {
+            zip_rx_counter.fetch_add(1, Ordering::SeqCst);
+            if zip_signaltx.send(SuccessSignal::Zip(new_zip)).is_err() {
+                return;
+            }
+        }
+    });
 
+    let groups = Arc::new(GroupRegistry::new());
+    let success_groups = Arc::clone(&groups);
+    let success_budget = Arc::clone(&budget);
     let _ = thread::spawn(move || {
         info!("generated games checker started");
-        let mut max = initial_zips;
-        loop {
-            if let Ok(msg) = ziprx.recv_timeout(Duration::from_secs(1)) {
-                info!(max, msg);
-                debug_assert!(max <= msg);
-                if msg.gt(&max) {
-                    info!(max, msg, "increased");
-                    info!("count: {}", msg);
-                    break;
+        let signal = loop {
+            match signalrx.recv_timeout(Duration::from_secs(1)) {
+                Ok(signal) => break signal,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    debug!("timed out waiting for a success signal");
                 }
-                if msg.lt(&max) {
-                    info!(
-                        "generated games appear to have shrunk, msg: {}, max: {}",
-                        msg, max,
-                    );
-                    max = msg;
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    error!("success channel disconnected, giving up");
+                    return;
                 }
-            } else {
-                debug!("timed out on receiving message from worker threads");
             }
-        }
-        info!("successfully generated a multiworld, exiting...");
-        let _ = signal::kill(Pid::from_raw(0), Signal::SIGINT);
+        };
+        let path = match signal {
+            SuccessSignal::Zip(new_zip) => {
+                info!(path = %new_zip.path.display(), "successfully generated a multiworld");
+                Some(new_zip.path)
+            }
+            SuccessSignal::Pattern { worker, line } => {
+                info!(worker, %line, "worker matched a success pattern");
+                None
+            }
+        };
+        event::emit(
+            json_events,
+            &Event::Success {
+                path,
+                total_attempts: success_budget.total_attempts(),
+            },
+        );
+        info!("shutting down workers...");
+        success_budget.declare_success();
+        success_groups.shutdown(SHUTDOWN_GRACE);
         process::exit(0); // c:
     });
 
-    let count_zips_closure = || how_many_zips(&archipelago_dir);
+    let monitor_groups = Arc::clone(&groups);
+    let monitor_budget = Arc::clone(&budget);
+    let _ = thread::spawn(move || loop {
+        thread::sleep(BUDGET_POLL_INTERVAL);
+        if monitor_budget.budget_exhausted() {
+            let reason = monitor_budget.exhaustion_reason();
+            error!(
+                total_attempts = monitor_budget.total_attempts(),
+                %reason,
+                "exhausted without success, aborting"
+            );
+            event::emit(json_events, &Event::Aborted { reason });
+            monitor_budget.stop();
+            monitor_groups.shutdown(SHUTDOWN_GRACE);
+            process::exit(1);
+        }
+    });
+
     debug!("starting workers");
     thread::scope(move |scope| {
-        for _ in 1..jobs + 1 {
-            let ziptx = ziptx.clone();
+        for worker in 0..jobs {
             let bin = bin.clone();
             let passthrough_args = args.options.clone();
-            scope.spawn(move || loop {
-                let Ok(mut child) = generate_multiworld(&bin, passthrough_args.clone()) else {
-                    error!("subprocess is malformed");
-                    continue;
-                };
-
-                debug!("subprocess spawned by worker");
-                let Some(mut stdout) = child.stdout.take() else {
-                    error!("could not acquire stdout, stopping subprocess");
-                    let _ = child.kill();
-                    continue;
-                };
-                debug!("subprocess' stdout acquired by worker");
-                let mut output = String::new();
-                debug!("reading subprocess stdout");
-                let _ = stdout.read_to_string(&mut output);
-                debug!(output);
-                match count_zips_closure() {
-                    Ok(counted_zips) => {
-                        let _ = {
-                            info!(counted_zips);
-                            debug!("{}", output);
-                            ziptx.send(counted_zips)
-                        };
+            let groups = Arc::clone(&groups);
+            let signaltx = signaltx.clone();
+            let budget = Arc::clone(&budget);
+            let attempt_config = Arc::clone(&attempt_config);
+            scope.spawn(move || {
+                let mut consecutive_failures = 0_u32;
+                while let Some(attempt) = budget.reserve_attempt() {
+                    debug!(worker, attempt, "starting attempt");
+                    match run_attempt(
+                        worker as usize,
+                        &bin,
+                        passthrough_args.clone(),
+                        &groups,
+                        &attempt_config,
+                    ) {
+                        Ok(Verdict::Success { line }) => {
+                            let _ = signaltx.send(SuccessSignal::Pattern {
+                                worker: worker as usize,
+                                line,
+                            });
+                            return;
+                        }
+                        Ok(Verdict::Failure { line }) => {
+                            error!(worker, %line, "attempt failed a fail-pattern check");
+                            consecutive_failures += 1;
+                        }
+                        Ok(Verdict::Abort { line }) => {
+                            error!(worker, %line, "attempt matched a non-retryable abort-pattern");
+                            let reason = format!("abort-pattern matched: {line}");
+                            event::emit(attempt_config.json_events, &Event::Aborted { reason });
+                            budget.stop();
+                            groups.shutdown(SHUTDOWN_GRACE);
+                            process::exit(1);
+                        }
+                        Ok(Verdict::Unknown) => {
+                            consecutive_failures = 0;
+                        }
+                        Err(e) => {
+                            error!(worker, ?e, "attempt failed");
+                            consecutive_failures += 1;
+                        }
                     }
-                    Err(e) => {
-                        error!(?e);
-                        let _ = child.kill();
-                        error!(output)
+                    if consecutive_failures > 0 {
+                        let backoff = (BACKOFF_BASE
+                            * 2_u32.saturating_pow(consecutive_failures - 1))
+                        .min(BACKOFF_CAP);
+                        debug!(worker, ?backoff, consecutive_failures, "backing off");
+                        thread::sleep(backoff);
                     }
-                };
+                }
+                debug!(worker, "attempt budget exhausted, worker stopping");
             });
         }
         debug!("workers spawned");
@@ -174,8 +369,92 @@ fn how_many_zips(folder: &Path) -> Result<usize> {
     Ok(count)
 }
 
+/// Spawns one generator attempt, drains its stdout and stderr to
+/// completion (on a helper thread so a hung generator can't hang the
+/// worker past `attempt_timeout`), classifies the combined output, and
+/// reaps the process group regardless of how the attempt ends.
+#[instrument(skip(groups, config))]
+fn run_attempt(
+    worker: usize,
+    bin: &str,
+    args: Vec<String>,
+    groups: &GroupRegistry,
+    config: &AttemptConfig,
+) -> Result<Verdict> {
+    let started = Instant::now();
+    let zips_before = config.zip_counter.load(Ordering::SeqCst);
+
+    let mut child = generate_multiworld(bin, args)?;
+    let pid = child.id() as i32;
+    groups.register(worker, pid);
+    event::emit(config.json_events, &Event::AttemptStarted { worker, pid });
+
+    let outcome = (|| -> Result<Verdict> {
+        let stdout = child
+            .inner()
+            .stdout
+            .take()
+            .context("could not acquire subprocess stdout")?;
+        let stderr = child
+            .inner()
+            .stderr
+            .take()
+            .context("could not acquire subprocess stderr")?;
+        debug!("draining subprocess stdout and stderr concurrently");
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(capture::drain(stdout, stderr));
+        });
+
+        let output = match config.attempt_timeout {
+            Some(timeout) => match rx.recv_timeout(timeout) {
+                Ok(result) => result?,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    anyhow::bail!("attempt exceeded {:?} timeout", timeout)
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    anyhow::bail!("drain thread disconnected without a result")
+                }
+            },
+            None => rx
+                .recv()
+                .context("drain thread disconnected without a result")??,
+        };
+
+        debug!(stdout = %output.stdout, "subprocess stdout");
+        if !output.stderr.is_empty() {
+            debug!(stderr = %output.stderr, "subprocess stderr");
+        }
+        Ok(config.classifier.classify(&output.combined()))
+    })();
+
+    if outcome.is_err() {
+        let _ = child.kill();
+    }
+    let exit_code = child.wait().ok().and_then(|status| status.code());
+    // zip_counter is shared across every worker, so this delta is "zips
+    // that landed anywhere while this attempt ran", not "zips this
+    // attempt produced" -- see the caveat on Event::AttemptFinished.
+    let zips_after = config.zip_counter.load(Ordering::SeqCst);
+    event::emit(
+        config.json_events,
+        &Event::AttemptFinished {
+            worker,
+            exit_code,
+            duration_ms: started.elapsed().as_millis(),
+            zip_delta: zips_after as i64 - zips_before as i64,
+        },
+    );
+    groups.unregister(worker);
+    outcome
+}
+
+/// Spawns the launcher in its own, fresh process group (via `setsid` on
+/// unix) so that a later `shutdown` can terminate the whole generator
+/// subtree -- including any Python children it forks -- by signalling the
+/// group rather than just the PID we spawned.
 #[instrument]
-fn generate_multiworld(bin: &str, args: Vec<String>) -> Result<Child> {
+fn generate_multiworld(bin: &str, args: Vec<String>) -> Result<GroupChild> {
     debug!("spawning generator");
     let generator = if !args.is_empty() {
         let args = Vec::from_iter(args.into_iter().map(|a| format!("--{}", a)));
@@ -186,19 +465,19 @@ fn generate_multiworld(bin: &str, args: Vec<String>) -> Result<Child> {
             .stderr(Stdio::piped())
             .stdout(Stdio::piped())
             .stdin(Stdio::piped())
-            .spawn()
+            .group_spawn()
     } else {
         Command::new(bin)
             .arg("Generate")
             .stderr(Stdio::piped())
             .stdout(Stdio::piped())
             .stdin(Stdio::piped())
-            .spawn()
+            .group_spawn()
     };
 
     debug!(?generator);
     let mut child = generator?;
-    match child.stdin.take() {
+    match child.inner().stdin.take() {
         Some(mut stdin) => {
             let _ = stdin.write_all(b"\n");
         }
@@ -209,7 +488,7 @@ fn generate_multiworld(bin: &str, args: Vec<String>) -> Result<Child> {
     Ok(child)
 }
 
-fn init_tracing() -> Result<()> {
+fn init_tracing(format: Format) -> Result<()> {
     let _ = dotenvy::dotenv();
     let subscriber = tracing_subscriber::fmt().pretty().finish();
     let _guard = tracing::subscriber::set_default(subscriber);
@@ -221,12 +500,18 @@ fn init_tracing() -> Result<()> {
         .from_env_lossy();
     info!("env filter: {}", filter);
 
+    // In `--format json` mode stdout is reserved for the JSONL event
+    // stream, so the pretty tracing output moves to stderr.
+    let console_writer = match format {
+        Format::Text => fmt::writer::BoxMakeWriter::new(std::io::stdout),
+        Format::Json => fmt::writer::BoxMakeWriter::new(std::io::stderr),
+    };
     let console_layer = fmt::Layer::default()
         .with_thread_ids(true)
         .with_file(true)
         .with_line_number(true)
         .with_target(true)
-        .with_writer(std::io::stdout);
+        .with_writer(console_writer);
 
     let registry = Registry::default();
 
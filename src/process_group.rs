@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use tracing::{debug, info, instrument, warn};
+
+/// Tracks the process-group leader of every worker's current child so that
+/// a successful run (or a shutdown request) can reap the whole generator
+/// subtree instead of just the launcher process we spawned directly.
+#[derive(Default)]
+pub struct GroupRegistry {
+    groups: Mutex<HashMap<usize, i32>>,
+}
+
+impl GroupRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the group leader PGID currently owned by `worker`, replacing
+    /// whatever it was tracking before.
+    pub fn register(&self, worker: usize, pgid: i32) {
+        self.groups.lock().unwrap().insert(worker, pgid);
+    }
+
+    /// Stops tracking `worker`'s group, e.g. once its child has been reaped.
+    pub fn unregister(&self, worker: usize) {
+        self.groups.lock().unwrap().remove(&worker);
+    }
+
+    /// Sends `SIGTERM` to every tracked process group, waits up to `grace`
+    /// for them to exit, then escalates to `SIGKILL` on anything still
+    /// alive. Safe to call from the success path or from a timeout/abort
+    /// path -- it only ever acts on what's currently registered.
+    #[instrument(skip(self))]
+    pub fn shutdown(&self, grace: Duration) {
+        let pgids: Vec<i32> = self.groups.lock().unwrap().values().copied().collect();
+        if pgids.is_empty() {
+            debug!("no process groups registered, nothing to shut down");
+            return;
+        }
+
+        for pgid in &pgids {
+            info!(pgid, "sending SIGTERM to process group");
+            if let Err(e) = signal::kill(Pid::from_raw(-pgid), Signal::SIGTERM) {
+                debug!(
+                    pgid,
+                    ?e,
+                    "SIGTERM delivery failed, group likely already gone"
+                );
+            }
+        }
+
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            if pgids.iter().copied().all(|pgid| !group_alive(pgid)) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        for pgid in pgids {
+            if group_alive(pgid) {
+                warn!(pgid, "process group survived grace period, sending SIGKILL");
+                let _ = signal::kill(Pid::from_raw(-pgid), Signal::SIGKILL);
+            }
+        }
+        debug!("graceful shutdown complete");
+    }
+}
+
+fn group_alive(pgid: i32) -> bool {
+    // Signal 0 performs no-op delivery but still reports ESRCH once every
+    // process in the group has exited.
+    signal::kill(Pid::from_raw(-pgid), None).is_ok()
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, instrument, warn};
+
+/// Debounce window over which bursts of filesystem events for the same
+/// path are coalesced into a single notification. Generators tend to
+/// write a zip's bytes, fsync, then rename it into place in quick
+/// succession, which would otherwise fire multiple events per multiworld.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// A completed (closed/renamed-into-place) `.zip` that appeared under the
+/// watched directory.
+#[derive(Debug, Clone)]
+pub struct NewZip {
+    pub path: PathBuf,
+}
+
+/// Tracks, per path, the last time a completed-write event was seen for
+/// it, so that an in-progress zip's debounce window can't be clobbered by
+/// an unrelated zip finishing at the same moment -- the default `--jobs
+/// 4` makes several workers finishing close together the common case,
+/// not a corner case.
+struct Debouncer {
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Records a completed-write event for `path`, (re)starting its
+    /// debounce window.
+    fn touch(&mut self, path: PathBuf, now: Instant) {
+        self.pending.insert(path, now);
+    }
+
+    /// Removes and returns every path whose debounce window has elapsed
+    /// as of `now`.
+    fn ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let settled: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, &seen)| now.saturating_duration_since(seen) >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &settled {
+            self.pending.remove(path);
+        }
+        settled
+    }
+
+    /// How long until the next pending path's debounce window elapses, or
+    /// `DEBOUNCE` if nothing is pending -- used as the poll interval so
+    /// the watcher thread doesn't busy-loop.
+    fn next_wait(&self, now: Instant) -> Duration {
+        self.pending
+            .values()
+            .map(|&seen| (seen + DEBOUNCE).saturating_duration_since(now))
+            .min()
+            .unwrap_or(DEBOUNCE)
+    }
+}
+
+/// Watches `dir` for newly completed `.zip` files and forwards each one,
+/// debounced, over `tx`. Returns the live `RecommendedWatcher` -- drop it
+/// to stop watching.
+#[instrument(skip(tx))]
+pub fn watch_zips(dir: &Path, tx: mpsc::SyncSender<NewZip>) -> Result<RecommendedWatcher> {
+    let (raw_tx, raw_rx) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(raw_tx).context("failed to initialize filesystem watcher")?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch '{}'", dir.display()))?;
+
+    std::thread::spawn(move || {
+        let mut debouncer = Debouncer::new();
+        loop {
+            let wait = debouncer.next_wait(Instant::now());
+            match raw_rx.recv_timeout(wait) {
+                Ok(Ok(event)) => {
+                    if is_completed_write(&event.kind) {
+                        for path in event.paths {
+                            if path.extension().is_some_and(|ext| ext == "zip") {
+                                debug!(path = %path.display(), "zip write observed, debouncing");
+                                debouncer.touch(path, Instant::now());
+                            }
+                        }
+                    }
+                }
+                Ok(Err(e)) => warn!(?e, "watcher error"),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            for path in debouncer.ready(Instant::now()) {
+                debug!(path = %path.display(), "new zip settled");
+                if tx.send(NewZip { path }).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// True for the event kinds that indicate a `.zip` has actually finished
+/// being written: a rename into place, or a close after writing. A bare
+/// `Create` is deliberately excluded -- a generator that writes its zip
+/// directly (no temp-file-plus-rename) would otherwise fire the instant
+/// the file is opened, against an empty or partial file.
+fn is_completed_write(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To))
+            | EventKind::Modify(ModifyKind::Name(RenameMode::Any))
+            | EventKind::Access(notify::event::AccessKind::Close(
+                notify::event::AccessMode::Write
+            ))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_paths_debounce_independently() {
+        let mut debouncer = Debouncer::new();
+        let t0 = Instant::now();
+        let a = PathBuf::from("a.zip");
+        let b = PathBuf::from("b.zip");
+
+        debouncer.touch(a.clone(), t0);
+        // b arrives mid-way through a's debounce window; this must not
+        // drop a's pending state.
+        debouncer.touch(b.clone(), t0 + Duration::from_millis(100));
+
+        // Just past a's window, only a is ready.
+        let ready = debouncer.ready(t0 + DEBOUNCE + Duration::from_millis(1));
+        assert_eq!(ready, vec![a]);
+
+        // Just past b's window, only b is ready.
+        let ready =
+            debouncer.ready(t0 + Duration::from_millis(100) + DEBOUNCE + Duration::from_millis(1));
+        assert_eq!(ready, vec![b]);
+    }
+
+    #[test]
+    fn retouching_a_path_restarts_its_window() {
+        let mut debouncer = Debouncer::new();
+        let t0 = Instant::now();
+        let path = PathBuf::from("a.zip");
+
+        debouncer.touch(path.clone(), t0);
+        // Re-touched just before the window would have elapsed.
+        debouncer.touch(path.clone(), t0 + Duration::from_millis(199));
+
+        assert!(debouncer.ready(t0 + DEBOUNCE).is_empty());
+        assert_eq!(
+            debouncer.ready(t0 + Duration::from_millis(199) + DEBOUNCE),
+            vec![path]
+        );
+    }
+
+    #[test]
+    fn bare_create_is_not_a_completed_write() {
+        assert!(!is_completed_write(&EventKind::Create(
+            notify::event::CreateKind::File
+        )));
+        assert!(is_completed_write(&EventKind::Access(
+            notify::event::AccessKind::Close(notify::event::AccessMode::Write)
+        )));
+    }
+}